@@ -3,69 +3,637 @@
 //! Confines agent file operations to their workspace directory.
 //! Prevents path traversal, symlink escapes, and access outside the sandbox.
 
-use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
 
-/// Resolve a user-supplied path within a workspace sandbox.
+use glob::Pattern;
+
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+/// Forbidden nested directory names that must never appear as a component
+/// of an audited path, regardless of the sandbox's allow/deny scope.
+const FORBIDDEN_COMPONENTS: &[&str] = &[".git", ".hg"];
+
+/// Maximum nesting depth of symlink resolution (a symlink whose target
+/// passes through another symlink, and so on) while auditing a single
+/// path, to bound recursion on a symlink cycle.
+const MAX_SYMLINK_DEPTH: usize = 32;
+
+/// Normalize a path lexically, without touching the filesystem.
 ///
-/// - Rejects `..` components outright.
-/// - Relative paths are joined with `workspace_root`.
-/// - Absolute paths are checked against the workspace root after canonicalization.
-/// - For new files: canonicalizes the parent directory and appends the filename.
-/// - The final canonical path must start with the canonical workspace root.
-pub fn resolve_sandbox_path(user_path: &str, workspace_root: &Path) -> Result<PathBuf, String> {
-    let path = Path::new(user_path);
+/// Resolves `.` and `..` components by manipulating the path itself, the
+/// way Cargo and Deno do it, so it works even when the path (or its
+/// ancestors) doesn't exist yet. A `..` with nothing left to pop (a
+/// leading `..`, or one directly after the root) is dropped; callers that
+/// forbid `..` outright should reject it before calling this function.
+///
+/// [`PathAuditor::audit_walk`] uses this to collapse `..` components
+/// before checking containment, so a relative symlink target that walks
+/// back out past the root can't hide behind a raw, un-normalized
+/// `starts_with` check.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut components = path.components().peekable();
+    let mut ret = if let Some(c @ Component::Prefix(..)) = components.peek().copied() {
+        components.next();
+        PathBuf::from(c.as_os_str())
+    } else {
+        PathBuf::new()
+    };
+
+    for component in components {
+        match component {
+            Component::Prefix(..) => unreachable!(),
+            Component::RootDir => ret.push(component.as_os_str()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                ret.pop();
+            }
+            Component::Normal(c) => ret.push(c),
+        }
+    }
+
+    ret
+}
+
+/// Split a glob pattern into its fixed (non-glob) leading path and the
+/// remaining glob suffix, e.g. `"workspace/**"` -> (`workspace`, `"**"`).
+fn split_fixed_prefix(pattern: &str) -> (PathBuf, String) {
+    fn is_glob(component: Component) -> bool {
+        matches!(component, Component::Normal(s) if
+            s.to_string_lossy().chars().any(|c| matches!(c, '*' | '?' | '[' | ']' | '{' | '}')))
+    }
+
+    let path = Path::new(pattern);
+    let mut fixed = PathBuf::new();
+    let mut rest = PathBuf::new();
+    let mut in_glob = false;
 
-    // Reject any `..` components
     for component in path.components() {
-        if matches!(component, std::path::Component::ParentDir) {
-            return Err("Path traversal denied: '..' components are forbidden".to_string());
+        in_glob = in_glob || is_glob(component);
+        if in_glob {
+            rest.push(component);
+        } else {
+            fixed.push(component);
+        }
+    }
+
+    (fixed, rest.to_string_lossy().into_owned())
+}
+
+/// Audits a path one component at a time against a sandbox root, the way
+/// Mercurial's `path_auditor` does.
+///
+/// Canonicalizing the whole candidate and checking `starts_with` hides
+/// *which* component caused an escape and is racy: a component can be
+/// swapped out between the audit and the eventual open. `PathAuditor`
+/// instead walks the path from the root, re-auditing any symlink's target
+/// before trusting it, and names the exact offending component on
+/// failure. Audited prefixes are cached on the auditor instance, so
+/// reusing one `PathAuditor` for many audits — as [`list_sandbox_dir`]
+/// does for an entire directory walk — skips re-auditing a prefix it's
+/// already seen. [`Sandbox::resolve`] and [`Sandbox::resolve_with_mode`]
+/// build a fresh, short-lived `PathAuditor` per call instead, since each
+/// call may target a different `base`, so that cache reuse doesn't apply
+/// there — they only pay for a single walk of the path being resolved.
+/// This is what [`Sandbox::resolve`] uses under [`ResolveMode::Canonical`]
+/// — the default, and what ordinary sandboxed reads and writes go
+/// through.
+pub struct PathAuditor {
+    root: PathBuf,
+    audited: HashSet<PathBuf>,
+}
+
+impl PathAuditor {
+    /// Create an auditor rooted at `root`, canonicalizing it up front.
+    pub fn new(root: &Path) -> Result<Self, String> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve workspace root: {e}"))?;
+        Ok(Self {
+            root,
+            audited: HashSet::new(),
+        })
+    }
+
+    /// Audit `user_path`, returning the real (symlink-resolved) path if
+    /// every component stays inside the root.
+    pub fn audit(&mut self, user_path: &str) -> Result<PathBuf, String> {
+        let path = Path::new(user_path);
+
+        for component in path.components() {
+            if matches!(component, Component::ParentDir) {
+                return Err("Path traversal denied: '..' components are forbidden".to_string());
+            }
+        }
+
+        let candidate = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        };
+
+        self.audit_walk(&candidate, 0)
+    }
+
+    /// Like [`Self::audit`], but returns `candidate` as given — symlinks
+    /// intact — alongside the fully dereferenced real path, for callers
+    /// that need the sandbox guarantee without losing the symlink itself
+    /// (e.g. writing through an intentional in-workspace symlink, or
+    /// reporting the path the user actually typed). A symlink whose target
+    /// escapes the root is still rejected. Callers that also apply
+    /// allow/deny scope (e.g. [`Sandbox::resolve_with_mode`]) must check it
+    /// against the real path too, or a symlink that stays inside the root
+    /// but points at a denied file would bypass the deny rule.
+    pub fn audit_preserving_symlinks(&mut self, candidate: &Path) -> Result<(PathBuf, PathBuf), String> {
+        if !path_within_root(candidate, &self.root) {
+            return Err("Access denied: path resolves outside workspace".to_string());
+        }
+        let real_path = self.audit_walk(candidate, 0)?;
+        Ok((candidate.to_path_buf(), real_path))
+    }
+
+    /// Audit `user_path` and open the audited path with `O_NOFOLLOW`,
+    /// returning the handle so the caller operates on the opened file
+    /// rather than resolving the path again later. `O_NOFOLLOW` refuses
+    /// a final component that has become a symlink since the audit; the
+    /// directories above it are resolved again by the open itself.
+    #[cfg(unix)]
+    pub fn audit_and_open(&mut self, user_path: &str) -> Result<(PathBuf, File), String> {
+        let audited = self.audit(user_path)?;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&audited)
+            .map_err(|e| format!("Failed to open audited path '{}': {e}", audited.display()))?;
+        Ok((audited, file))
+    }
+
+    /// Walk `candidate` component by component from the root, following
+    /// and re-auditing symlinks as they're encountered.
+    fn audit_walk(&mut self, candidate: &Path, depth: usize) -> Result<PathBuf, String> {
+        if depth > MAX_SYMLINK_DEPTH {
+            return Err("Too many levels of symbolic links".to_string());
         }
+
+        // Collapse `.`/`..` lexically before walking. `candidate` is
+        // always absolute here (either joined against the already-
+        // canonical root, or a symlink target resolved against its
+        // parent), so a trailing `starts_with(self.root)` check on the
+        // raw, un-normalized path isn't enough — a relative symlink
+        // target like `../../../../tmp/secret.txt` lexically starts with
+        // the root's own components and would slip past that check
+        // without ever popping back out through them.
+        let candidate = normalize_path(candidate);
+
+        let mut prefix = PathBuf::new();
+        for component in candidate.components() {
+            prefix.push(component);
+
+            if self.audited.contains(&prefix) {
+                continue;
+            }
+
+            if let Component::Normal(name) = component {
+                if FORBIDDEN_COMPONENTS.contains(&name.to_string_lossy().as_ref()) {
+                    return Err(format!(
+                        "Access denied: '{}' is a forbidden directory",
+                        name.to_string_lossy()
+                    ));
+                }
+            }
+
+            let metadata = match std::fs::symlink_metadata(&prefix) {
+                Ok(metadata) => metadata,
+                // Doesn't exist yet — nothing further to audit (e.g. the
+                // final component of a new file).
+                Err(_) => continue,
+            };
+            let file_type = metadata.file_type();
+
+            if file_type.is_symlink() {
+                let target = std::fs::read_link(&prefix)
+                    .map_err(|e| format!("Failed to read symlink '{}': {e}", prefix.display()))?;
+                let target = if target.is_absolute() {
+                    target
+                } else {
+                    prefix
+                        .parent()
+                        .unwrap_or_else(|| Path::new(""))
+                        .join(target)
+                };
+
+                let real_target = self.audit_walk(&target, depth + 1).map_err(|_| {
+                    format!(
+                        "Access denied: symlink '{}' points outside workspace",
+                        component.as_os_str().to_string_lossy()
+                    )
+                })?;
+                if !path_within_root(&real_target, &self.root) {
+                    return Err(format!(
+                        "Access denied: symlink '{}' points outside workspace",
+                        component.as_os_str().to_string_lossy()
+                    ));
+                }
+                prefix = real_target;
+            } else {
+                #[cfg(unix)]
+                if file_type.is_char_device()
+                    || file_type.is_block_device()
+                    || file_type.is_fifo()
+                    || file_type.is_socket()
+                {
+                    return Err(format!(
+                        "Access denied: '{}' is a device or special file",
+                        prefix.display()
+                    ));
+                }
+            }
+
+            self.audited.insert(prefix.clone());
+        }
+
+        if !path_within_root(&prefix, &self.root) {
+            return Err("Access denied: path resolves outside workspace".to_string());
+        }
+
+        Ok(prefix)
     }
+}
 
-    // Build the candidate path
-    let candidate = if path.is_absolute() {
-        path.to_path_buf()
+/// Check whether `candidate` is contained within `root`, the way
+/// `candidate.starts_with(root)` does — except on Windows, where both
+/// sides are simplified first.
+///
+/// `Path::canonicalize` returns verbatim `\\?\C:\...` paths on Windows, so
+/// a plain `starts_with` can fail (or spuriously succeed) when only one
+/// side happens to be verbatim — e.g. `root` came from `canonicalize()`
+/// but `candidate` is an absolute symlink target read straight off disk.
+/// Stripping the verbatim prefix from both sides before comparing fixes
+/// that. Unix is unaffected; this is a no-op there.
+fn path_within_root(candidate: &Path, root: &Path) -> bool {
+    if cfg!(windows) {
+        simplify_windows_path(candidate).starts_with(simplify_windows_path(root))
     } else {
-        workspace_root.join(path)
+        candidate.starts_with(root)
+    }
+}
+
+/// Strip a Windows verbatim (`\\?\`) prefix and anchor disk-relative
+/// prefixes (`C:foo`, with no following root separator) against the
+/// current directory. A no-op on non-Windows targets.
+#[cfg(windows)]
+fn simplify_windows_path(path: &Path) -> PathBuf {
+    let anchored = match path.components().next() {
+        Some(Component::Prefix(prefix)) if is_disk_relative(path, prefix) => {
+            std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
     };
 
-    // Canonicalize the workspace root
-    let canon_root = workspace_root
+    let lossy = anchored.to_string_lossy();
+    match lossy.strip_prefix(r"\\?\") {
+        Some(stripped) => PathBuf::from(stripped),
+        None => anchored,
+    }
+}
+
+#[cfg(windows)]
+fn is_disk_relative(path: &Path, prefix: std::path::PrefixComponent<'_>) -> bool {
+    matches!(prefix.kind(), std::path::Prefix::Disk(_))
+        && !matches!(path.components().nth(1), Some(Component::RootDir))
+}
+
+#[cfg(not(windows))]
+fn simplify_windows_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// An allow/deny glob scope for confining agent file operations.
+///
+/// Modeled on Tauri's filesystem scope: rather than a single
+/// `workspace_root`, a sandbox holds ordered allow and deny glob patterns
+/// (e.g. allow `workspace/**`, `/tmp/agent-*/**`; deny `**/.git/**`,
+/// `**/*.pem`). This lets a host grant an agent several workspace roots,
+/// carve out read-only areas, or blocklist secret files, instead of being
+/// limited to one hard-coded directory. Deny always wins over allow.
+#[derive(Default)]
+pub struct Sandbox {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl Sandbox {
+    /// An empty sandbox that allows nothing until patterns are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A sandbox equivalent to the previous single-root behavior: allows
+    /// everything under `root`.
+    pub fn workspace(root: &Path) -> Result<Self, String> {
+        let mut sandbox = Self::new();
+        sandbox.allow(&format!("{}/**", root.display()))?;
+        Ok(sandbox)
+    }
+
+    /// Allow paths matching `pattern`.
+    ///
+    /// The pattern's fixed (non-glob) prefix is canonicalized so
+    /// `workspace/**` matches the real resolved location (symlinks
+    /// included) rather than however the caller happened to spell it. The
+    /// fixed prefix itself is also allowed, as an exact match, so the
+    /// directories containing the glob remain listable on the way down.
+    pub fn allow(&mut self, pattern: &str) -> Result<(), String> {
+        let (full, prefix) = self.canonicalize_pattern(pattern)?;
+        self.allow.push(prefix);
+        self.allow.push(full);
+        Ok(())
+    }
+
+    /// Deny paths matching `pattern`. Deny patterns always win over allow
+    /// patterns, regardless of insertion order.
+    pub fn deny(&mut self, pattern: &str) -> Result<(), String> {
+        let (full, _prefix) = self.canonicalize_pattern(pattern)?;
+        self.deny.push(full);
+        Ok(())
+    }
+
+    fn canonicalize_pattern(&self, pattern: &str) -> Result<(Pattern, Pattern), String> {
+        let (fixed, rest) = split_fixed_prefix(pattern);
+
+        // An un-anchored glob (e.g. `**/*.pem`, `**/.git/**`) has no fixed
+        // literal prefix to canonicalize — match it as written instead of
+        // erroring on `"".canonicalize()`.
+        if fixed.as_os_str().is_empty() {
+            let pattern_glob =
+                Pattern::new(pattern).map_err(|e| format!("Invalid glob pattern '{pattern}': {e}"))?;
+            return Ok((pattern_glob.clone(), pattern_glob));
+        }
+
+        let canon_fixed = fixed
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve scope pattern '{pattern}': {e}"))?;
+
+        let full = if rest.is_empty() {
+            canon_fixed.clone()
+        } else {
+            canon_fixed.join(&rest)
+        };
+
+        let full_pattern = Pattern::new(&full.to_string_lossy())
+            .map_err(|e| format!("Invalid glob pattern '{pattern}': {e}"))?;
+        let prefix_pattern = Pattern::new(&canon_fixed.to_string_lossy())
+            .map_err(|e| format!("Invalid glob pattern '{pattern}': {e}"))?;
+        Ok((full_pattern, prefix_pattern))
+    }
+
+    /// Resolve a user-supplied path, joining relative paths against `base`,
+    /// and check it against this sandbox's allow/deny scope.
+    ///
+    /// Equivalent to `resolve_with_mode(user_path, base, ResolveMode::Canonical)`.
+    pub fn resolve(&self, user_path: &str, base: &Path) -> Result<PathBuf, String> {
+        self.resolve_with_mode(user_path, base, ResolveMode::Canonical)
+    }
+
+    /// Resolve a user-supplied path under the given [`ResolveMode`], joining
+    /// relative paths against `base`, and check it against this sandbox's
+    /// allow/deny scope.
+    pub fn resolve_with_mode(
+        &self,
+        user_path: &str,
+        base: &Path,
+        mode: ResolveMode,
+    ) -> Result<PathBuf, String> {
+        let (candidate, real_path) = match mode {
+            ResolveMode::Canonical => {
+                let resolved = PathAuditor::new(base)?.audit(user_path)?;
+                (resolved.clone(), resolved)
+            }
+            ResolveMode::PreserveSymlinks => {
+                let lexical = resolve_lexical_preserving_symlinks(user_path, base)?;
+                PathAuditor::new(base)?.audit_preserving_symlinks(&lexical)?
+            }
+        };
+        let candidate_str = candidate.to_string_lossy();
+        let real_path_str = real_path.to_string_lossy();
+
+        // Check deny rules against both the path as returned and its real
+        // (symlink-resolved) location — under `PreserveSymlinks` these can
+        // differ, and a symlink that stays inside the root but points at a
+        // denied file must not bypass the deny rule.
+        if self.deny.iter().any(|p| p.matches(&candidate_str) || p.matches(&real_path_str)) {
+            return Err(format!(
+                "Access denied: path '{user_path}' matches a sandbox deny rule"
+            ));
+        }
+        // Likewise, the allow scope must cover both the path as returned
+        // and its real location — a symlink that stays inside an allowed
+        // glob but targets a file outside it must not be handed out.
+        if !self.allow.iter().any(|p| p.matches(&candidate_str))
+            || !self.allow.iter().any(|p| p.matches(&real_path_str))
+        {
+            return Err(format!(
+                "Access denied: path '{user_path}' resolves outside the sandbox's allowed scope. \
+                 If you have an MCP filesystem server configured, use the \
+                 mcp_filesystem_* tools (e.g. mcp_filesystem_read_file, \
+                 mcp_filesystem_list_directory) to access files outside \
+                 the workspace."
+            ));
+        }
+
+        Ok(candidate)
+    }
+}
+
+/// How [`Sandbox::resolve_with_mode`] should interpret a user path.
+pub enum ResolveMode {
+    /// Canonicalize fully, following all symlinks. What [`Sandbox::resolve`] uses.
+    Canonical,
+    /// Resolve `.`/`..` lexically and verify the result stays inside the
+    /// workspace without dereferencing symlinks, so an intentional
+    /// in-workspace symlink — or the path the user actually typed — is
+    /// preserved in the returned path. A symlink whose *target* escapes
+    /// the workspace is still rejected.
+    PreserveSymlinks,
+}
+
+/// Resolve `user_path` the way `path_abs`'s `absolute` does: lexically,
+/// without touching the filesystem or following symlinks.
+///
+/// Joins a relative path onto the canonical form of `root`, drops `.`
+/// components, and resolves a *leading* run of `..` against `root` —
+/// matching a shell that lets you `cd ..` from the workspace root — but
+/// rejects any interior `..` outright. This alone only guarantees the
+/// lexical path stays in bounds; pair it with [`PathAuditor::audit_preserving_symlinks`]
+/// to also reject a symlink whose target escapes the root.
+fn resolve_lexical_preserving_symlinks(user_path: &str, root: &Path) -> Result<PathBuf, String> {
+    let canon_root = root
         .canonicalize()
         .map_err(|e| format!("Failed to resolve workspace root: {e}"))?;
 
-    // Canonicalize the candidate (or its parent for new files)
-    let canon_candidate = if candidate.exists() {
-        candidate
-            .canonicalize()
-            .map_err(|e| format!("Failed to resolve path: {e}"))?
+    let path = Path::new(user_path);
+    let mut ret = if path.is_absolute() {
+        PathBuf::new()
     } else {
-        // For new files: canonicalize the parent and append the filename
-        let parent = candidate
-            .parent()
-            .ok_or_else(|| "Invalid path: no parent directory".to_string())?;
-        let filename = candidate
-            .file_name()
-            .ok_or_else(|| "Invalid path: no filename".to_string())?;
-        let canon_parent = parent
-            .canonicalize()
-            .map_err(|e| format!("Failed to resolve parent directory: {e}"))?;
-        canon_parent.join(filename)
+        canon_root.clone()
     };
+    let mut seen_normal = false;
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => ret.push(component.as_os_str()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if seen_normal {
+                    return Err(
+                        "Path traversal denied: interior '..' components are forbidden"
+                            .to_string(),
+                    );
+                }
+                if !ret.pop() {
+                    return Err("Access denied: path escapes the workspace root".to_string());
+                }
+            }
+            Component::Normal(c) => {
+                seen_normal = true;
+                ret.push(c);
+            }
+        }
+    }
 
-    // Verify the canonical path is inside the workspace
-    if !canon_candidate.starts_with(&canon_root) {
+    if !path_within_root(&ret, &canon_root) {
         return Err(format!(
-            "Access denied: path '{}' resolves outside workspace. \
-             If you have an MCP filesystem server configured, use the \
-             mcp_filesystem_* tools (e.g. mcp_filesystem_read_file, \
-             mcp_filesystem_list_directory) to access files outside \
-             the workspace.",
-            user_path
+            "Access denied: path '{user_path}' resolves outside workspace"
         ));
     }
 
-    Ok(canon_candidate)
+    Ok(ret)
+}
+
+/// A single entry returned by [`list_sandbox_dir`].
+#[derive(Debug, Clone)]
+pub struct SandboxDirEntry {
+    pub name: String,
+    pub relative_path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<std::time::SystemTime>,
+}
+
+/// Recursively list a directory inside the sandbox.
+///
+/// The natural read-side complement to [`Sandbox::resolve`]-style
+/// resolution: agents need to enumerate their workspace (tree view, glob
+/// search) without every caller hand-rolling a traversal-safe directory
+/// walk. Every discovered entry is run back through a [`PathAuditor`]
+/// rooted at `workspace_root`, so a symlinked subdirectory pointing
+/// outside the root is pruned rather than followed. `max_depth` bounds how
+/// many levels below `user_path` are descended (`0` lists only its
+/// immediate contents); `include_hidden` controls whether dotfile entries
+/// are returned.
+pub fn list_sandbox_dir(
+    user_path: &str,
+    workspace_root: &Path,
+    max_depth: usize,
+    include_hidden: bool,
+) -> Result<Vec<SandboxDirEntry>, String> {
+    let mut auditor = PathAuditor::new(workspace_root)?;
+    let list_root = auditor.audit(user_path)?;
+    if !list_root.is_dir() {
+        return Err(format!("'{user_path}' is not a directory"));
+    }
+
+    let mut entries = Vec::new();
+    walk_sandbox_dir(
+        &mut auditor,
+        &list_root,
+        &list_root,
+        &list_root,
+        0,
+        max_depth,
+        include_hidden,
+        &mut entries,
+    )?;
+    Ok(entries)
+}
+
+/// Walk `dir` (the real, symlink-resolved directory to actually read) while
+/// tracking `logical_dir`, the entry's pre-resolution location in the tree.
+/// The two diverge once a symlinked subdirectory is descended into: `dir`
+/// becomes the symlink's real target so `read_dir` is safe to call, but
+/// `relative_path` must still be built from `logical_dir` so a listing
+/// reports paths under the symlink's logical position rather than under
+/// wherever it happens to point.
+#[allow(clippy::too_many_arguments)]
+fn walk_sandbox_dir(
+    auditor: &mut PathAuditor,
+    list_root: &Path,
+    logical_dir: &Path,
+    dir: &Path,
+    depth: usize,
+    max_depth: usize,
+    include_hidden: bool,
+    out: &mut Vec<SandboxDirEntry>,
+) -> Result<(), String> {
+    let read_dir =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read directory '{}': {e}", dir.display()))?;
+
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry.map_err(|e| format!("Failed to read directory entry: {e}"))?;
+        let name = dir_entry.file_name().to_string_lossy().into_owned();
+        if !include_hidden && name.starts_with('.') {
+            continue;
+        }
+
+        let path = dir_entry.path();
+        let logical_path = logical_dir.join(&name);
+
+        // Audit every discovered entry so a symlinked subdirectory (or
+        // file) pointing outside the workspace is pruned rather than
+        // followed.
+        let audited = match auditor.audit(&path.to_string_lossy()) {
+            Ok(audited) => audited,
+            Err(_) => continue,
+        };
+
+        // Read metadata from the audited (symlink-resolved) path, not the
+        // directory entry itself — `dir_entry.metadata()` is an `lstat`
+        // and would report a symlink's own size/mtime rather than its
+        // target's.
+        let metadata = match std::fs::metadata(&audited) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        let is_dir = metadata.is_dir();
+        let relative_path = logical_path.strip_prefix(list_root).unwrap_or(&logical_path).to_path_buf();
+
+        out.push(SandboxDirEntry {
+            name,
+            relative_path,
+            is_dir,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+
+        if is_dir && depth < max_depth {
+            walk_sandbox_dir(
+                auditor,
+                list_root,
+                &logical_path,
+                &audited,
+                depth + 1,
+                max_depth,
+                include_hidden,
+                out,
+            )?;
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -80,7 +648,8 @@ mod tests {
         std::fs::create_dir_all(&data_dir).unwrap();
         std::fs::write(data_dir.join("test.txt"), "hello").unwrap();
 
-        let result = resolve_sandbox_path("data/test.txt", dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("data/test.txt", dir.path());
         assert!(result.is_ok());
         let resolved = result.unwrap();
         assert!(resolved.starts_with(dir.path().canonicalize().unwrap()));
@@ -92,7 +661,8 @@ mod tests {
         std::fs::write(dir.path().join("file.txt"), "ok").unwrap();
         let abs_path = dir.path().join("file.txt");
 
-        let result = resolve_sandbox_path(abs_path.to_str().unwrap(), dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve(abs_path.to_str().unwrap(), dir.path());
         assert!(result.is_ok());
     }
 
@@ -102,7 +672,8 @@ mod tests {
         let outside = std::env::temp_dir().join("outside_test.txt");
         std::fs::write(&outside, "nope").unwrap();
 
-        let result = resolve_sandbox_path(outside.to_str().unwrap(), dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve(outside.to_str().unwrap(), dir.path());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Access denied"));
 
@@ -112,7 +683,8 @@ mod tests {
     #[test]
     fn test_dotdot_component_blocked() {
         let dir = TempDir::new().unwrap();
-        let result = resolve_sandbox_path("../../../etc/passwd", dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("../../../etc/passwd", dir.path());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Path traversal denied"));
     }
@@ -123,13 +695,335 @@ mod tests {
         let data_dir = dir.path().join("data");
         std::fs::create_dir_all(&data_dir).unwrap();
 
-        let result = resolve_sandbox_path("data/new_file.txt", dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("data/new_file.txt", dir.path());
         assert!(result.is_ok());
         let resolved = result.unwrap();
         assert!(resolved.starts_with(dir.path().canonicalize().unwrap()));
         assert!(resolved.ends_with("new_file.txt"));
     }
 
+    #[test]
+    fn test_nonexistent_nested_directories() {
+        let dir = TempDir::new().unwrap();
+
+        // `reports/` doesn't exist yet, so neither does its child `2024/`.
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("data/reports/2024/out.txt", dir.path());
+        assert!(result.is_ok());
+        let resolved = result.unwrap();
+        assert!(resolved.starts_with(dir.path().canonicalize().unwrap()));
+        assert!(resolved.ends_with("data/reports/2024/out.txt"));
+    }
+
+    #[test]
+    fn test_auditor_rejects_deep_nonexistent_path_outside_root() {
+        let dir = TempDir::new().unwrap();
+        let outside = std::env::temp_dir().join("workspace_sandbox_lexical_escape_test");
+
+        // Neither `outside` nor its parent need exist; an absolute path
+        // entirely unrelated to the root must still be rejected, not just
+        // returned because none of its components could be stat'd.
+        let mut auditor = PathAuditor::new(dir.path()).unwrap();
+        let result = auditor.audit(outside.join("deep/new_file.txt").to_str().unwrap());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Access denied"));
+    }
+
+    #[test]
+    fn test_normalize_path_resolves_dot_components() {
+        let normalized = normalize_path(Path::new("a/./b/../c"));
+        assert_eq!(normalized, PathBuf::from("a/c"));
+    }
+
+    #[test]
+    fn test_deny_wins_over_allow() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("secret.pem"), "key").unwrap();
+
+        let mut sandbox = Sandbox::workspace(dir.path()).unwrap();
+        sandbox.deny("**/*.pem").unwrap();
+
+        let result = sandbox.resolve("secret.pem", dir.path());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("deny rule"));
+    }
+
+    #[test]
+    fn test_multiple_allow_roots() {
+        let dir_a = TempDir::new().unwrap();
+        let dir_b = TempDir::new().unwrap();
+        std::fs::write(dir_a.path().join("a.txt"), "a").unwrap();
+        std::fs::write(dir_b.path().join("b.txt"), "b").unwrap();
+
+        let mut sandbox = Sandbox::new();
+        sandbox.allow(&format!("{}/**", dir_a.path().display())).unwrap();
+        sandbox.allow(&format!("{}/**", dir_b.path().display())).unwrap();
+
+        assert!(sandbox.resolve("a.txt", dir_a.path()).is_ok());
+        assert!(sandbox.resolve("b.txt", dir_b.path()).is_ok());
+    }
+
+    #[test]
+    fn test_auditor_allows_plain_path() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("data")).unwrap();
+        std::fs::write(dir.path().join("data/test.txt"), "hi").unwrap();
+
+        let mut auditor = PathAuditor::new(dir.path()).unwrap();
+        let result = auditor.audit("data/test.txt");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_auditor_blocks_forbidden_component() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+
+        let mut auditor = PathAuditor::new(dir.path()).unwrap();
+        let result = auditor.audit(".git/config");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("forbidden directory"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_auditor_names_offending_symlink() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+        let link_path = dir.path().join("escape");
+        std::os::unix::fs::symlink(outside.path(), &link_path).unwrap();
+
+        let mut auditor = PathAuditor::new(dir.path()).unwrap();
+        let result = auditor.audit("escape/secret.txt");
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(err.contains("symlink 'escape'"));
+        assert!(err.contains("points outside workspace"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_relative_symlink_escape_blocked() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+        // A *relative* symlink target whose own `..` components walk back
+        // out past the workspace root before descending into `outside`.
+        // Joined lexically against the root this still starts with the
+        // root's own components, so a plain `starts_with` (without
+        // collapsing `..` first) would wrongly treat it as contained.
+        let outside_rel = outside.path().strip_prefix("/").unwrap();
+        let target = format!("{}{}/secret.txt", "../".repeat(32), outside_rel.display());
+        std::os::unix::fs::symlink(target, dir.path().join("escape")).unwrap();
+
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("escape", dir.path());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Access denied"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_simplify_windows_path_strips_verbatim_prefix() {
+        let verbatim = Path::new(r"\\?\C:\work\repo");
+        assert_eq!(simplify_windows_path(verbatim), PathBuf::from(r"C:\work\repo"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_path_within_root_ignores_verbatim_mismatch() {
+        let root = Path::new(r"\\?\C:\work\repo");
+        let candidate = Path::new(r"C:\work\repo\sub\file.txt");
+        assert!(path_within_root(candidate, root));
+    }
+
+    #[test]
+    fn test_list_sandbox_dir_lists_immediate_contents() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "b").unwrap();
+
+        let entries = list_sandbox_dir(".", dir.path(), 0, false).unwrap();
+        let names: HashSet<_> = entries.iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, HashSet::from(["a.txt".to_string(), "sub".to_string()]));
+    }
+
+    #[test]
+    fn test_list_sandbox_dir_respects_max_depth() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "b").unwrap();
+
+        let shallow = list_sandbox_dir(".", dir.path(), 0, false).unwrap();
+        assert!(!shallow.iter().any(|e| e.name == "b.txt"));
+
+        let deep = list_sandbox_dir(".", dir.path(), 1, false).unwrap();
+        assert!(deep.iter().any(|e| e.name == "b.txt"));
+    }
+
+    #[test]
+    fn test_list_sandbox_dir_skips_hidden_by_default() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(".hidden"), "h").unwrap();
+        std::fs::write(dir.path().join("visible.txt"), "v").unwrap();
+
+        let entries = list_sandbox_dir(".", dir.path(), 0, false).unwrap();
+        assert!(!entries.iter().any(|e| e.name == ".hidden"));
+
+        let entries = list_sandbox_dir(".", dir.path(), 0, true).unwrap();
+        assert!(entries.iter().any(|e| e.name == ".hidden"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_sandbox_dir_prunes_escaping_symlink() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("escape")).unwrap();
+
+        let entries = list_sandbox_dir(".", dir.path(), 2, false).unwrap();
+        assert!(!entries.iter().any(|e| e.name == "secret.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_sandbox_dir_reports_target_metadata_for_symlinked_file() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("real.txt"), "hello world").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("real.txt"), dir.path().join("link.txt")).unwrap();
+
+        let entries = list_sandbox_dir(".", dir.path(), 0, false).unwrap();
+        let link_entry = entries.iter().find(|e| e.name == "link.txt").unwrap();
+        let real_entry = entries.iter().find(|e| e.name == "real.txt").unwrap();
+
+        // Both should report the real file's size ("hello world" == 11
+        // bytes), not the symlink object's own (much smaller) lstat size.
+        assert_eq!(link_entry.size, real_entry.size);
+        assert_eq!(link_entry.size, 11);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_sandbox_dir_reports_logical_path_under_symlinked_directory() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("real")).unwrap();
+        std::fs::write(dir.path().join("real/file.txt"), "hi").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("real"), dir.path().join("link")).unwrap();
+
+        let entries = list_sandbox_dir(".", dir.path(), 2, false).unwrap();
+
+        // The file under the symlinked directory must be reported at its
+        // logical position (under `link/`), not at the real directory's
+        // own location that the symlink happens to resolve to.
+        assert!(entries
+            .iter()
+            .any(|e| e.relative_path == Path::new("link/file.txt")));
+        assert!(entries
+            .iter()
+            .any(|e| e.relative_path == Path::new("real/file.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_symlinks_mode_keeps_in_workspace_symlink() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("real")).unwrap();
+        std::fs::write(dir.path().join("real/file.txt"), "hi").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("real"), dir.path().join("link")).unwrap();
+
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let resolved = sandbox
+            .resolve_with_mode("link/file.txt", dir.path(), ResolveMode::PreserveSymlinks)
+            .unwrap();
+
+        // The symlink component itself is preserved, unlike `Canonical` mode.
+        assert!(resolved.components().any(|c| c.as_os_str() == "link"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_symlinks_mode_rejects_escaping_target() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("escape")).unwrap();
+
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result =
+            sandbox.resolve_with_mode("escape/secret.txt", dir.path(), ResolveMode::PreserveSymlinks);
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_symlinks_mode_deny_rule_covers_symlink_target() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("secrets")).unwrap();
+        std::fs::write(dir.path().join("secrets/api_key.txt"), "shh").unwrap();
+        std::fs::create_dir_all(dir.path().join("public")).unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("secrets/api_key.txt"),
+            dir.path().join("public/link"),
+        )
+        .unwrap();
+
+        let mut sandbox = Sandbox::workspace(dir.path()).unwrap();
+        sandbox
+            .deny(&format!("{}/secrets/**", dir.path().display()))
+            .unwrap();
+
+        // The symlink stays inside the workspace root, but its target is
+        // denied — the deny rule must still apply.
+        let result =
+            sandbox.resolve_with_mode("public/link", dir.path(), ResolveMode::PreserveSymlinks);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("deny rule"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_symlinks_mode_allow_scope_covers_symlink_target() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("secrets")).unwrap();
+        std::fs::write(dir.path().join("secrets/api_key.txt"), "shh").unwrap();
+        std::fs::create_dir_all(dir.path().join("public")).unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("secrets/api_key.txt"),
+            dir.path().join("public/link"),
+        )
+        .unwrap();
+
+        // Only `public/**` is allowed — `secrets/` is out of scope
+        // entirely, with no deny rule needed to exclude it.
+        let mut sandbox = Sandbox::new();
+        sandbox
+            .allow(&format!("{}/public/**", dir.path().display()))
+            .unwrap();
+
+        let result =
+            sandbox.resolve_with_mode("public/link", dir.path(), ResolveMode::PreserveSymlinks);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("allowed scope"));
+    }
+
+    #[test]
+    fn test_preserve_symlinks_mode_rejects_interior_dotdot() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result =
+            sandbox.resolve_with_mode("a/b/../../../etc/passwd", dir.path(), ResolveMode::PreserveSymlinks);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("interior"));
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_symlink_escape_blocked() {
@@ -141,7 +1035,8 @@ mod tests {
         let link_path = dir.path().join("escape");
         std::os::unix::fs::symlink(outside.path(), &link_path).unwrap();
 
-        let result = resolve_sandbox_path("escape/secret.txt", dir.path());
+        let sandbox = Sandbox::workspace(dir.path()).unwrap();
+        let result = sandbox.resolve("escape/secret.txt", dir.path());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Access denied"));
     }